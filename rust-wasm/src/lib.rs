@@ -16,6 +16,79 @@ pub struct TradeOpportunity {
     pub transport_cost: f64,
 }
 
+/// One candidate buy/sell quote pair, as fed into `calculate_opportunity`.
+struct OpportunityQuote {
+    item_id: String,
+    item_name: String,
+    buy_city: String,
+    sell_city: String,
+    buy_price: f64,
+    sell_price: f64,
+    quantity: u32,
+}
+
+/// One buy made during a `plan_route` leg.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RouteLegBuy {
+    pub item_id: String,
+    pub item_name: String,
+    pub quantity: u32,
+    pub buy_price: f64,
+    pub sell_price: f64,
+    pub profit: f64,
+}
+
+/// One leg of a `plan_route` itinerary: what to buy in `from_city` and sell in
+/// `to_city`, and the running profit total through this point in the route.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RouteLeg {
+    pub from_city: String,
+    pub to_city: String,
+    pub buys: Vec<RouteLegBuy>,
+    pub leg_profit: f64,
+    pub cumulative_profit: f64,
+}
+
+/// A silver amount stored as an exact count of thousandths-of-a-silver.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Silver(i128);
+
+const SILVER_SCALE: i128 = 1_000;
+
+impl Silver {
+    fn from_f64(value: f64) -> Self {
+        Silver((value * SILVER_SCALE as f64).round() as i128)
+    }
+
+    fn to_f64(self) -> f64 {
+        self.0 as f64 / SILVER_SCALE as f64
+    }
+
+    fn mul_u32(self, n: u32) -> Self {
+        Silver(self.0 * n as i128)
+    }
+
+    /// Multiply by a small fraction (e.g. a tax rate), rounding to the nearest
+    /// thousandth-of-a-silver.
+    fn mul_rate(self, rate: f64) -> Self {
+        Silver(((self.0 as f64) * rate).round() as i128)
+    }
+}
+
+impl std::ops::Add for Silver {
+    type Output = Silver;
+    fn add(self, rhs: Silver) -> Silver {
+        Silver(self.0 + rhs.0)
+    }
+}
+
+impl std::ops::Sub for Silver {
+    type Output = Silver;
+    fn sub(self, rhs: Silver) -> Silver {
+        Silver(self.0 - rhs.0)
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct MarketData {
     pub item_id: String,
@@ -24,77 +97,242 @@ pub struct MarketData {
     pub buy_price: f64,
     pub sell_price: f64,
     pub quantity: u32,
+    /// Unix timestamp (ms) the quote was observed at, used to discard stale listings
+    /// and to weight samples when computing a time-weighted average price.
+    pub timestamp: u64,
+}
+
+/// Time-weighted average price accumulator for one item/city.
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct TimeWeightedPrice {
+    points: Vec<(u64, f64)>,
 }
 
+#[wasm_bindgen]
+impl TimeWeightedPrice {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> TimeWeightedPrice {
+        TimeWeightedPrice { points: Vec::new() }
+    }
+
+    /// Add a `(timestamp, price)` sample, kept sorted by timestamp.
+    #[wasm_bindgen]
+    pub fn add_point(&mut self, timestamp: u64, price: f64) {
+        let pos = self
+            .points
+            .partition_point(|(existing_ts, _)| *existing_ts <= timestamp);
+        self.points.insert(pos, (timestamp, price));
+    }
+
+    /// Compute the time-weighted average price, or `None` with no samples.
+    #[wasm_bindgen]
+    pub fn twap(&self) -> Option<f64> {
+        match self.points.len() {
+            0 => None,
+            1 => Some(self.points[0].1),
+            _ => {
+                let mut weighted_sum = 0.0;
+                let mut total_dt = 0.0;
+                for pair in self.points.windows(2) {
+                    let (t0, p0) = pair[0];
+                    let (t1, _) = pair[1];
+                    let dt = (t1 - t0) as f64;
+                    weighted_sum += p0 * dt;
+                    total_dt += dt;
+                }
+                if total_dt > 0.0 {
+                    Some(weighted_sum / total_dt)
+                } else {
+                    self.points.last().map(|(_, price)| *price)
+                }
+            }
+        }
+    }
+}
+
+/// Zone counts between cities, keyed `from_city -> to_city -> zones`. Caller-supplied so
+/// non-royal destinations (black market, portal towns) get real distances instead of the
+/// `estimate_transport_cost` fallback.
+type DistanceMatrix = std::collections::HashMap<String, std::collections::HashMap<String, u32>>;
+
+/// Carry weight per unit, keyed by `item_id`.
+type ItemWeights = std::collections::HashMap<String, f64>;
+
 #[wasm_bindgen]
 pub struct TradeScanner {
     market_tax: f64,
     setup_fee: f64,
+    silver_per_zone: f64,
+    mount_weight_cap: f64,
 }
 
 #[wasm_bindgen]
 impl TradeScanner {
     #[wasm_bindgen(constructor)]
-    pub fn new() -> TradeScanner {
+    pub fn new(silver_per_zone: f64, mount_weight_cap: f64) -> TradeScanner {
         TradeScanner {
             market_tax: 0.045,  // 4.5%
             setup_fee: 0.015,   // 1.5%
+            silver_per_zone,
+            mount_weight_cap,
         }
     }
 
     /// Scan for arbitrage opportunities across all cities
-    /// Returns top N opportunities sorted by ROI
+    /// Returns top N opportunities sorted by ROI. `max_age_ms` (0 = disabled) drops
+    /// stale quotes per item before grouping; `use_twap` smooths each city's quote.
     #[wasm_bindgen]
     pub fn scan_opportunities(
         &self,
         market_data_js: JsValue,
         min_roi: f64,
         max_results: usize,
+        max_age_ms: u64,
+        use_twap: bool,
+        item_weights_js: JsValue,
+        distance_matrix_js: JsValue,
     ) -> Result<JsValue, JsValue> {
         let market_data: Vec<MarketData> = serde_wasm_bindgen::from_value(market_data_js)
             .map_err(|e| JsValue::from_str(&format!("Failed to parse market data: {}", e)))?;
+        let item_weights: ItemWeights = serde_wasm_bindgen::from_value(item_weights_js)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse item weights: {}", e)))?;
+        let distance_matrix: DistanceMatrix = serde_wasm_bindgen::from_value(distance_matrix_js)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse distance matrix: {}", e)))?;
 
+        let opportunities = self.scan_opportunities_internal(
+            market_data,
+            min_roi,
+            max_results,
+            max_age_ms,
+            use_twap,
+            &item_weights,
+            &distance_matrix,
+        );
+
+        serde_wasm_bindgen::to_value(&opportunities)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize results: {}", e)))
+    }
+
+    fn scan_opportunities_internal(
+        &self,
+        market_data: Vec<MarketData>,
+        min_roi: f64,
+        max_results: usize,
+        max_age_ms: u64,
+        use_twap: bool,
+        item_weights: &ItemWeights,
+        distance_matrix: &DistanceMatrix,
+    ) -> Vec<TradeOpportunity> {
         let mut opportunities = Vec::new();
 
-        // Group by item_id for cross-city comparison
+        // Group by item_id first, then by city, so each city contributes one
+        // representative quote per item (its TWAP when requested, otherwise its
+        // freshest raw quote).
         let mut items_by_id: std::collections::HashMap<String, Vec<&MarketData>> =
             std::collections::HashMap::new();
-
         for data in &market_data {
-            items_by_id
-                .entry(data.item_id.clone())
-                .or_insert_with(Vec::new)
-                .push(data);
+            items_by_id.entry(data.item_id.clone()).or_default().push(data);
+        }
+
+        // Discard stale quotes relative to the freshest timestamp *within each item*,
+        // not the whole batch — a liquid item's fresher quotes shouldn't cause a
+        // lower-liquidity item's otherwise-consistent listings to be dropped as stale.
+        let items_by_id: std::collections::HashMap<
+            String,
+            std::collections::HashMap<String, Vec<&MarketData>>,
+        > = items_by_id
+            .into_iter()
+            .map(|(item_id, samples)| {
+                let samples: Vec<&MarketData> = if max_age_ms == 0 {
+                    samples
+                } else {
+                    let newest_ts = samples.iter().map(|d| d.timestamp).max().unwrap_or(0);
+                    samples
+                        .into_iter()
+                        .filter(|d| newest_ts.saturating_sub(d.timestamp) <= max_age_ms)
+                        .collect()
+                };
+                let mut by_city: std::collections::HashMap<String, Vec<&MarketData>> =
+                    std::collections::HashMap::new();
+                for data in samples {
+                    by_city.entry(data.city.clone()).or_default().push(data);
+                }
+                (item_id, by_city)
+            })
+            .collect();
+
+        struct CityQuote {
+            item_name: String,
+            buy_price: f64,
+            sell_price: f64,
+            quantity: u32,
         }
 
         // Find arbitrage opportunities
         for (item_id, cities) in items_by_id.iter() {
-            for buy_city_data in cities.iter() {
-                for sell_city_data in cities.iter() {
-                    if buy_city_data.city == sell_city_data.city {
+            let mut quotes: std::collections::HashMap<String, CityQuote> =
+                std::collections::HashMap::new();
+
+            for (city, samples) in cities.iter() {
+                let latest = samples.iter().max_by_key(|d| d.timestamp).unwrap();
+
+                let (buy_price, sell_price) = if use_twap && samples.len() > 1 {
+                    let mut buy_twap = TimeWeightedPrice::new();
+                    let mut sell_twap = TimeWeightedPrice::new();
+                    for sample in samples.iter() {
+                        buy_twap.add_point(sample.timestamp, sample.buy_price);
+                        sell_twap.add_point(sample.timestamp, sample.sell_price);
+                    }
+                    (
+                        buy_twap.twap().unwrap_or(latest.buy_price),
+                        sell_twap.twap().unwrap_or(latest.sell_price),
+                    )
+                } else {
+                    (latest.buy_price, latest.sell_price)
+                };
+
+                quotes.insert(
+                    city.clone(),
+                    CityQuote {
+                        item_name: latest.item_name.clone(),
+                        buy_price,
+                        sell_price,
+                        quantity: latest.quantity,
+                    },
+                );
+            }
+
+            for (buy_city, buy_quote) in quotes.iter() {
+                for (sell_city, sell_quote) in quotes.iter() {
+                    if buy_city == sell_city {
                         continue;
                     }
 
-                    let buy_price = buy_city_data.buy_price;
-                    let sell_price = sell_city_data.sell_price;
+                    let buy_price = buy_quote.buy_price;
+                    let sell_price = sell_quote.sell_price;
 
                     if buy_price <= 0.0 || sell_price <= 0.0 {
                         continue;
                     }
 
-                    let quantity = buy_city_data.quantity.min(sell_city_data.quantity);
+                    let quantity = buy_quote.quantity.min(sell_quote.quantity);
                     if quantity == 0 {
                         continue;
                     }
 
                     let opportunity = self.calculate_opportunity(
-                        item_id.clone(),
-                        buy_city_data.item_name.clone(),
-                        buy_city_data.city.clone(),
-                        sell_city_data.city.clone(),
-                        buy_price,
-                        sell_price,
-                        quantity,
+                        OpportunityQuote {
+                            item_id: item_id.clone(),
+                            item_name: buy_quote.item_name.clone(),
+                            buy_city: buy_city.clone(),
+                            sell_city: sell_city.clone(),
+                            buy_price,
+                            sell_price,
+                            quantity,
+                        },
+                        item_weights,
+                        distance_matrix,
                     );
 
                     if opportunity.roi >= min_roi {
@@ -110,40 +348,82 @@ impl TradeScanner {
         // Take top N results
         opportunities.truncate(max_results);
 
-        serde_wasm_bindgen::to_value(&opportunities)
-            .map_err(|e| JsValue::from_str(&format!("Failed to serialize results: {}", e)))
+        opportunities
+    }
+
+    /// Same as `scan_opportunities`, but ingests a postcard-encoded `Vec<MarketData>`
+    /// and returns a postcard-encoded `Vec<TradeOpportunity>` instead of round-tripping
+    /// through `JsValue`/JSON. Lets the JS side hand over a cached or network-delivered
+    /// `ArrayBuffer`/`Uint8Array` directly, which matters for large multi-city dumps
+    /// where JSON parse/stringify dominates. Freshness filtering, TWAP smoothing, item
+    /// weights, and the distance matrix are left at their defaults (off / empty) here;
+    /// use `scan_opportunities` when you need them.
+    #[wasm_bindgen]
+    pub fn scan_opportunities_bytes(
+        &self,
+        data: &[u8],
+        min_roi: f64,
+        max_results: usize,
+    ) -> Result<Vec<u8>, JsValue> {
+        let market_data: Vec<MarketData> = postcard::from_bytes(data)
+            .map_err(|e| JsValue::from_str(&format!("Failed to decode market data: {}", e)))?;
+
+        let opportunities = self.scan_opportunities_internal(
+            market_data,
+            min_roi,
+            max_results,
+            0,
+            false,
+            &ItemWeights::new(),
+            &DistanceMatrix::new(),
+        );
+
+        postcard::to_allocvec(&opportunities)
+            .map_err(|e| JsValue::from_str(&format!("Failed to encode results: {}", e)))
     }
 
     fn calculate_opportunity(
         &self,
-        item_id: String,
-        item_name: String,
-        buy_city: String,
-        sell_city: String,
-        buy_price: f64,
-        sell_price: f64,
-        quantity: u32,
+        quote: OpportunityQuote,
+        item_weights: &ItemWeights,
+        distance_matrix: &DistanceMatrix,
     ) -> TradeOpportunity {
-        let qty = quantity as f64;
+        let OpportunityQuote {
+            item_id,
+            item_name,
+            buy_city,
+            sell_city,
+            buy_price,
+            sell_price,
+            quantity,
+        } = quote;
+
+        let tax_rate = self.market_tax + self.setup_fee;
 
         // Calculate costs
-        let buy_total = buy_price * qty;
-        let buy_taxes = buy_total * (self.market_tax + self.setup_fee);
+        let buy_total = Silver::from_f64(buy_price).mul_u32(quantity);
+        let buy_taxes = buy_total.mul_rate(tax_rate);
 
-        // Estimate transport cost (simplified - 100 silver per zone)
-        let transport_cost = self.estimate_transport_cost(&buy_city, &sell_city, qty);
+        let transport_cost = Silver::from_f64(self.estimate_transport_cost(
+            &item_id,
+            &buy_city,
+            &sell_city,
+            quantity,
+            item_weights,
+            distance_matrix,
+        ));
 
         let total_cost = buy_total + buy_taxes + transport_cost;
 
         // Calculate revenue
-        let sell_total = sell_price * qty;
-        let sell_taxes = sell_total * (self.market_tax + self.setup_fee);
+        let sell_total = Silver::from_f64(sell_price).mul_u32(quantity);
+        let sell_taxes = sell_total.mul_rate(tax_rate);
         let net_revenue = sell_total - sell_taxes;
 
         // Calculate profit and ROI
         let profit = net_revenue - total_cost;
-        let roi = if total_cost > 0.0 {
-            (profit / total_cost) * 100.0
+        let roi = if total_cost.0 > 0 {
+            (profit.0 as f64 / total_cost.0 as f64) * 100.0
         } else {
             0.0
         };
@@ -156,27 +436,560 @@ impl TradeScanner {
             buy_price,
             sell_price,
             quantity,
-            profit,
+            profit: profit.to_f64(),
             roi,
-            taxes: buy_taxes + sell_taxes,
-            transport_cost,
+            taxes: (buy_taxes + sell_taxes).to_f64(),
+            transport_cost: transport_cost.to_f64(),
+        }
+    }
+
+    /// Transport cost = zones (from `distance_matrix`) × `silver_per_zone` × total weight.
+    fn estimate_transport_cost(
+        &self,
+        item_id: &str,
+        from_city: &str,
+        to_city: &str,
+        quantity: u32,
+        item_weights: &ItemWeights,
+        distance_matrix: &DistanceMatrix,
+    ) -> f64 {
+        let zones = distance_matrix
+            .get(from_city)
+            .and_then(|row| row.get(to_city))
+            .copied()
+            .unwrap_or(12); // fallback for city pairs missing from the caller-supplied matrix
+
+        let item_weight = item_weights.get(item_id).copied().unwrap_or(1.0);
+        let total_weight = item_weight * quantity as f64;
+
+        zones as f64 * self.silver_per_zone * total_weight
+    }
+
+    /// Build every profitable buy/sell opportunity across cities for the given market
+    /// snapshot, shared by `optimize_haul` and `plan_route`.
+    fn build_opportunities(
+        &self,
+        market_data: &[MarketData],
+        item_weights: &ItemWeights,
+        distance_matrix: &DistanceMatrix,
+    ) -> Vec<TradeOpportunity> {
+        let mut items_by_id: std::collections::HashMap<String, Vec<&MarketData>> =
+            std::collections::HashMap::new();
+        for data in market_data {
+            items_by_id.entry(data.item_id.clone()).or_default().push(data);
+        }
+
+        let mut opportunities = Vec::new();
+        for (item_id, cities) in items_by_id.iter() {
+            for buy_city_data in cities.iter() {
+                for sell_city_data in cities.iter() {
+                    if buy_city_data.city == sell_city_data.city {
+                        continue;
+                    }
+                    let buy_price = buy_city_data.buy_price;
+                    let sell_price = sell_city_data.sell_price;
+                    if buy_price <= 0.0 || sell_price <= 0.0 {
+                        continue;
+                    }
+                    let quantity = buy_city_data.quantity.min(sell_city_data.quantity);
+                    if quantity == 0 {
+                        continue;
+                    }
+                    let opportunity = self.calculate_opportunity(
+                        OpportunityQuote {
+                            item_id: item_id.clone(),
+                            item_name: buy_city_data.item_name.clone(),
+                            buy_city: buy_city_data.city.clone(),
+                            sell_city: sell_city_data.city.clone(),
+                            buy_price,
+                            sell_price,
+                            quantity,
+                        },
+                        item_weights,
+                        distance_matrix,
+                    );
+                    if opportunity.profit > 0.0 {
+                        opportunities.push(opportunity);
+                    }
+                }
+            }
+        }
+        opportunities
+    }
+
+    /// Select how much of each candidate opportunity to buy, maximizing profit within
+    /// the budget and the mount's carry-weight cap.
+    #[wasm_bindgen]
+    pub fn optimize_haul(
+        &self,
+        market_data_js: JsValue,
+        budget: f64,
+        carry_weight: f64,
+        item_weights_js: JsValue,
+        distance_matrix_js: JsValue,
+    ) -> Result<JsValue, JsValue> {
+        let market_data: Vec<MarketData> = serde_wasm_bindgen::from_value(market_data_js)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse market data: {}", e)))?;
+        let item_weights: ItemWeights = serde_wasm_bindgen::from_value(item_weights_js)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse item weights: {}", e)))?;
+        let distance_matrix: DistanceMatrix = serde_wasm_bindgen::from_value(distance_matrix_js)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse distance matrix: {}", e)))?;
+
+        // The mount's own weight cap always bounds whatever carry_weight the caller
+        // passes in.
+        let carry_weight = carry_weight.min(self.mount_weight_cap);
+
+        // Build every profitable buy/sell pair from the raw quotes. Unlike
+        // `scan_opportunities` there is no freshness filter, TWAP smoothing or
+        // min-ROI/top-N trimming (the knapsack below decides what's worth taking).
+        let opportunities = self.build_opportunities(&market_data, &item_weights, &distance_matrix);
+
+        let per_unit_profit: Vec<f64> = opportunities
+            .iter()
+            .map(|opp| opp.profit / opp.quantity as f64)
+            .collect();
+        let per_unit_weight: Vec<f64> = opportunities
+            .iter()
+            .map(|opp| {
+                item_weights
+                    .get(&opp.item_id)
+                    .copied()
+                    .unwrap_or(1.0)
+                    .max(0.0001)
+            })
+            .collect();
+        let per_unit_cost: Vec<f64> = opportunities
+            .iter()
+            .map(|opp| {
+                let buy_total = opp.buy_price * opp.quantity as f64;
+                let buy_taxes = buy_total * (self.market_tax + self.setup_fee);
+                (buy_total + buy_taxes + opp.transport_cost) / opp.quantity as f64
+            })
+            .collect();
+
+        let (_, selected_units) =
+            Self::budget_knapsack(&opportunities, &per_unit_profit, &per_unit_cost, budget);
+
+        // The DP above only respects the budget. Trim the pick back to the carry-weight
+        // cap by dropping units from whichever selected opportunity earns the least
+        // profit per unit of weight, worst first.
+        let mut selections: Vec<(usize, u32)> = selected_units;
+        selections.sort_by(|a, b| {
+            let ratio_a = per_unit_profit[a.0] / per_unit_weight[a.0];
+            let ratio_b = per_unit_profit[b.0] / per_unit_weight[b.0];
+            ratio_a.partial_cmp(&ratio_b).unwrap()
+        });
+
+        let mut total_weight: f64 = selections
+            .iter()
+            .map(|(idx, units)| per_unit_weight[*idx] * (*units as f64))
+            .sum();
+
+        let mut i = 0;
+        while total_weight > carry_weight && i < selections.len() {
+            let (idx, units) = &mut selections[i];
+            let w = per_unit_weight[*idx];
+            let excess = total_weight - carry_weight;
+            let units_to_drop = (excess / w).ceil() as u32;
+            let dropped = units_to_drop.min(*units);
+            *units -= dropped;
+            total_weight -= dropped as f64 * w;
+            if *units == 0 {
+                i += 1;
+            }
+        }
+
+        let mut hauled: Vec<TradeOpportunity> = selections
+            .into_iter()
+            .filter(|(_, units)| *units > 0)
+            .map(|(idx, units)| {
+                let opp = &opportunities[idx];
+                let mut hauled_opp = opp.clone();
+                hauled_opp.quantity = units;
+                hauled_opp.profit = per_unit_profit[idx] * units as f64;
+                hauled_opp.transport_cost = hauled_opp.transport_cost * units as f64
+                    / opp.quantity as f64;
+                hauled_opp.taxes = hauled_opp.taxes * units as f64 / opp.quantity as f64;
+                hauled_opp
+            })
+            .collect();
+        hauled.sort_by(|a, b| b.profit.partial_cmp(&a.profit).unwrap());
+
+        serde_wasm_bindgen::to_value(&hauled)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize results: {}", e)))
+    }
+
+    /// Shared bounded-knapsack core used by `optimize_haul` and `plan_route`. Returns
+    /// the best profit found and the selected `(opportunity index, quantity)` pairs.
+    fn budget_knapsack(
+        opportunities: &[TradeOpportunity],
+        per_unit_profit: &[f64],
+        per_unit_cost: &[f64],
+        budget: f64,
+    ) -> (f64, Vec<(usize, u32)>) {
+        // Bound the DP table size regardless of input size: a real multi-city scan can
+        // produce far more power-of-two "stacks" (below) than is safe to reconstruct
+        // with a full per-stack boolean matrix, so both the budget resolution and the
+        // number of stacks considered are capped.
+        const MAX_BUCKETS: usize = 5_000;
+        const MAX_STACKS: usize = 4_000;
+
+        let bucket_size = (budget / MAX_BUCKETS as f64).max(1.0);
+        let scaled_budget = ((budget / bucket_size).floor() as usize).min(MAX_BUCKETS);
+
+        struct Stack {
+            opp_index: usize,
+            units: u32,
+            cost_buckets: usize,
+            profit: f64,
+        }
+
+        let mut stacks = Vec::new();
+        for (idx, opp) in opportunities.iter().enumerate() {
+            let mut remaining = opp.quantity;
+            let mut size: u32 = 1;
+            while remaining > 0 {
+                let take = size.min(remaining);
+                let cost_buckets =
+                    ((per_unit_cost[idx] * take as f64) / bucket_size).ceil() as usize;
+                stacks.push(Stack {
+                    opp_index: idx,
+                    units: take,
+                    cost_buckets,
+                    profit: per_unit_profit[idx] * take as f64,
+                });
+                remaining -= take;
+                size *= 2;
+            }
+        }
+
+        // If there are more stacks than the DP table can afford, keep only the most
+        // profitable-per-bucket ones; the rest are dropped from consideration rather
+        // than blowing up memory.
+        if stacks.len() > MAX_STACKS {
+            stacks.sort_by(|a, b| {
+                let density_a = a.profit / a.cost_buckets.max(1) as f64;
+                let density_b = b.profit / b.cost_buckets.max(1) as f64;
+                density_b.partial_cmp(&density_a).unwrap()
+            });
+            stacks.truncate(MAX_STACKS);
+        }
+
+        // Reconstruction table, now bounded to at most MAX_STACKS × (MAX_BUCKETS + 1)
+        // booleans by the truncation above, instead of growing with the size of the
+        // candidate opportunity set.
+        let mut dp = vec![0.0f64; scaled_budget + 1];
+        let mut take = vec![vec![false; scaled_budget + 1]; stacks.len()];
+
+        for (s_idx, stack) in stacks.iter().enumerate() {
+            if stack.cost_buckets > scaled_budget {
+                continue;
+            }
+            for b in (stack.cost_buckets..=scaled_budget).rev() {
+                let candidate = dp[b - stack.cost_buckets] + stack.profit;
+                if candidate > dp[b] {
+                    dp[b] = candidate;
+                    take[s_idx][b] = true;
+                }
+            }
         }
+
+        let mut best_b = 0;
+        for b in 0..=scaled_budget {
+            if dp[b] > dp[best_b] {
+                best_b = b;
+            }
+        }
+
+        let mut selected_units: std::collections::HashMap<usize, u32> =
+            std::collections::HashMap::new();
+        let mut b = best_b;
+        for s_idx in (0..stacks.len()).rev() {
+            if take[s_idx][b] {
+                let stack = &stacks[s_idx];
+                *selected_units.entry(stack.opp_index).or_insert(0) += stack.units;
+                b -= stack.cost_buckets;
+            }
+        }
+
+        (dp[best_b], selected_units.into_iter().collect())
     }
 
-    fn estimate_transport_cost(&self, from_city: &str, to_city: &str, quantity: f64) -> f64 {
-        // Simplified distance matrix (zones between cities)
-        let distance = match (from_city, to_city) {
-            ("Caerleon", "Bridgewatch") | ("Bridgewatch", "Caerleon") => 8,
-            ("Caerleon", "Lymhurst") | ("Lymhurst", "Caerleon") => 8,
-            ("Caerleon", "Martlock") | ("Martlock", "Caerleon") => 8,
-            ("Caerleon", "Fort Sterling") | ("Fort Sterling", "Caerleon") => 8,
-            ("Caerleon", "Thetford") | ("Thetford", "Caerleon") => 8,
-            _ => 12, // Cross-royal city trades
+    /// Plan the most profitable round trip of up to `max_hops` cities starting and
+    /// ending at `start_city`.
+    #[wasm_bindgen]
+    pub fn plan_route(
+        &self,
+        market_data_js: JsValue,
+        start_city: String,
+        max_hops: usize,
+        budget: f64,
+        item_weights_js: JsValue,
+        distance_matrix_js: JsValue,
+    ) -> Result<JsValue, JsValue> {
+        let market_data: Vec<MarketData> = serde_wasm_bindgen::from_value(market_data_js)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse market data: {}", e)))?;
+        let item_weights: ItemWeights = serde_wasm_bindgen::from_value(item_weights_js)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse item weights: {}", e)))?;
+        let distance_matrix: DistanceMatrix = serde_wasm_bindgen::from_value(distance_matrix_js)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse distance matrix: {}", e)))?;
+
+        let route = self
+            .plan_route_internal(market_data, start_city, max_hops, budget, &item_weights, &distance_matrix)
+            .map_err(|e| JsValue::from_str(&e))?;
+
+        serde_wasm_bindgen::to_value(&route)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize results: {}", e)))
+    }
+
+    fn plan_route_internal(
+        &self,
+        market_data: Vec<MarketData>,
+        start_city: String,
+        max_hops: usize,
+        budget: f64,
+        item_weights: &ItemWeights,
+        distance_matrix: &DistanceMatrix,
+    ) -> Result<Vec<RouteLeg>, String> {
+        let cities: Vec<String> = {
+            let mut set = std::collections::BTreeSet::new();
+            for data in &market_data {
+                set.insert(data.city.clone());
+            }
+            set.into_iter().collect()
         };
 
-        // Base cost: 100 silver per zone, scales with quantity
-        let weight_factor = (quantity / 100.0).max(1.0);
-        (distance as f64) * 100.0 * weight_factor
+        // The DFS below is exponential in both inputs (city permutations times an
+        // O(cities^2) budget_knapsack call per pair), so bound them rather than
+        // trusting a caller reachable directly from JS.
+        const MAX_CITIES: usize = 16;
+        const MAX_HOPS: usize = 8;
+        if cities.len() > MAX_CITIES {
+            return Err(format!(
+                "plan_route: market data spans {} cities, exceeding the {} limit",
+                cities.len(),
+                MAX_CITIES
+            ));
+        }
+        if max_hops > MAX_HOPS {
+            return Err(format!(
+                "plan_route: max_hops {} exceeds the {} limit",
+                max_hops, MAX_HOPS
+            ));
+        }
+
+        let opportunities = self.build_opportunities(&market_data, item_weights, distance_matrix);
+
+        // Candidate opportunities per ordered city pair. Cheap to precompute up front;
+        // unlike the actual buy selection, it doesn't depend on capital on hand.
+        let mut leg_candidates: std::collections::HashMap<(String, String), Vec<TradeOpportunity>> =
+            std::collections::HashMap::new();
+        for opp in &opportunities {
+            leg_candidates
+                .entry((opp.buy_city.clone(), opp.sell_city.clone()))
+                .or_default()
+                .push(opp.clone());
+        }
+
+        // The call-invariant inputs to the DFS below, bundled so the recursive fn
+        // itself stays under clippy's argument-count limit.
+        struct RouteDfsContext<'a> {
+            scanner: &'a TradeScanner,
+            start: &'a str,
+            max_hops: usize,
+            leg_candidates: &'a std::collections::HashMap<(String, String), Vec<TradeOpportunity>>,
+            item_weights: &'a ItemWeights,
+            cities: &'a [String],
+        }
+
+        // Bounded DFS over the small city set for the most profitable cycle through
+        // `start_city` visiting at most `max_hops` other cities (so at most
+        // `max_hops + 1` legs, counting the closing leg back). Capital carries forward between
+        // legs (starting `budget`, plus each leg's profit once its sale settles), so
+        // a leg's buy selection is re-run against what's actually on hand at that
+        // point in the route rather than the flat starting budget.
+        fn dfs(
+            ctx: &RouteDfsContext,
+            current: &str,
+            visited: &mut Vec<String>,
+            hops: usize,
+            capital: f64,
+            legs_so_far: &mut Vec<RouteLeg>,
+            best: &mut Option<(f64, Vec<RouteLeg>)>,
+        ) {
+            if hops >= 1 {
+                if let Some(candidates) = ctx
+                    .leg_candidates
+                    .get(&(current.to_string(), ctx.start.to_string()))
+                {
+                    if let Some(closing_leg) =
+                        ctx.scanner
+                            .best_leg(current, ctx.start, candidates, ctx.item_weights, capital)
+                    {
+                        let total_profit = legs_so_far.iter().map(|l| l.leg_profit).sum::<f64>()
+                            + closing_leg.leg_profit;
+                        if best.as_ref().map_or(true, |(p, _)| total_profit > *p) {
+                            let mut path_legs = legs_so_far.clone();
+                            path_legs.push(closing_leg);
+                            *best = Some((total_profit, path_legs));
+                        }
+                    }
+                }
+            }
+
+            if hops >= ctx.max_hops {
+                return;
+            }
+
+            for next in ctx.cities {
+                if visited.contains(next) {
+                    continue;
+                }
+                if let Some(candidates) = ctx
+                    .leg_candidates
+                    .get(&(current.to_string(), next.clone()))
+                {
+                    if let Some(leg) =
+                        ctx.scanner.best_leg(current, next, candidates, ctx.item_weights, capital)
+                    {
+                        let next_capital = capital + leg.leg_profit;
+                        visited.push(next.clone());
+                        legs_so_far.push(leg);
+                        dfs(ctx, next, visited, hops + 1, next_capital, legs_so_far, best);
+                        legs_so_far.pop();
+                        visited.pop();
+                    }
+                }
+            }
+        }
+
+        let ctx = RouteDfsContext {
+            scanner: self,
+            start: &start_city,
+            max_hops,
+            leg_candidates: &leg_candidates,
+            item_weights,
+            cities: &cities,
+        };
+        let mut best: Option<(f64, Vec<RouteLeg>)> = None;
+        let mut visited = vec![start_city.clone()];
+        let mut legs_so_far = Vec::new();
+
+        dfs(&ctx, &start_city, &mut visited, 0, budget, &mut legs_so_far, &mut best);
+
+        let route: Vec<RouteLeg> = match best {
+            Some((_, mut legs)) => {
+                let mut cumulative = 0.0;
+                for leg in &mut legs {
+                    cumulative += leg.leg_profit;
+                    leg.cumulative_profit = cumulative;
+                }
+                legs
+            }
+            None => Vec::new(),
+        };
+
+        Ok(route)
+    }
+
+    /// Run the budget knapsack and `optimize_haul`'s weight-trim pass for one route
+    /// leg's candidate opportunities against the capital actually on hand. Returns
+    /// `None` if nothing profitable fits.
+    fn best_leg(
+        &self,
+        from: &str,
+        to: &str,
+        candidates: &[TradeOpportunity],
+        item_weights: &ItemWeights,
+        capital: f64,
+    ) -> Option<RouteLeg> {
+        let per_unit_profit: Vec<f64> = candidates
+            .iter()
+            .map(|opp| opp.profit / opp.quantity as f64)
+            .collect();
+        let per_unit_weight: Vec<f64> = candidates
+            .iter()
+            .map(|opp| {
+                item_weights
+                    .get(&opp.item_id)
+                    .copied()
+                    .unwrap_or(1.0)
+                    .max(0.0001)
+            })
+            .collect();
+        let per_unit_cost: Vec<f64> = candidates
+            .iter()
+            .map(|opp| {
+                let buy_total = opp.buy_price * opp.quantity as f64;
+                let buy_taxes = buy_total * (self.market_tax + self.setup_fee);
+                (buy_total + buy_taxes + opp.transport_cost) / opp.quantity as f64
+            })
+            .collect();
+
+        let (_, selected) =
+            Self::budget_knapsack(candidates, &per_unit_profit, &per_unit_cost, capital);
+
+        // The DP above only respects the budget. Trim the pick back to the
+        // mount's carry-weight cap the same way `optimize_haul` does, dropping
+        // units from whichever selection earns the least profit per unit of
+        // weight, worst first.
+        let mut selections = selected;
+        selections.sort_by(|a, b| {
+            let ratio_a = per_unit_profit[a.0] / per_unit_weight[a.0];
+            let ratio_b = per_unit_profit[b.0] / per_unit_weight[b.0];
+            ratio_a.partial_cmp(&ratio_b).unwrap()
+        });
+
+        let mut total_weight: f64 = selections
+            .iter()
+            .map(|(idx, units)| per_unit_weight[*idx] * (*units as f64))
+            .sum();
+
+        let mut i = 0;
+        while total_weight > self.mount_weight_cap && i < selections.len() {
+            let (idx, units) = &mut selections[i];
+            let w = per_unit_weight[*idx];
+            let excess = total_weight - self.mount_weight_cap;
+            let units_to_drop = (excess / w).ceil() as u32;
+            let dropped = units_to_drop.min(*units);
+            *units -= dropped;
+            total_weight -= dropped as f64 * w;
+            if *units == 0 {
+                i += 1;
+            }
+        }
+
+        let leg_profit: f64 = selections
+            .iter()
+            .map(|(idx, units)| per_unit_profit[*idx] * *units as f64)
+            .sum();
+        if leg_profit <= 0.0 {
+            return None;
+        }
+
+        let buys = selections
+            .into_iter()
+            .filter(|(_, units)| *units > 0)
+            .map(|(idx, units)| {
+                let opp = &candidates[idx];
+                RouteLegBuy {
+                    item_id: opp.item_id.clone(),
+                    item_name: opp.item_name.clone(),
+                    quantity: units,
+                    buy_price: opp.buy_price,
+                    sell_price: opp.sell_price,
+                    profit: per_unit_profit[idx] * units as f64,
+                }
+            })
+            .collect();
+
+        Some(RouteLeg {
+            from_city: from.to_string(),
+            to_city: to.to_string(),
+            buys,
+            leg_profit,
+            cumulative_profit: 0.0, // filled in once a winning cycle is chosen
+        })
     }
 }
 
@@ -190,3 +1003,268 @@ pub fn init_panic_hook() {
 pub fn greet(name: &str) -> String {
     format!("Hello, {}! WASM is working!", name)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opp(item_id: &str, quantity: u32, profit: f64) -> TradeOpportunity {
+        TradeOpportunity {
+            item_id: item_id.to_string(),
+            item_name: item_id.to_string(),
+            buy_city: "Martlock".to_string(),
+            sell_city: "Caerleon".to_string(),
+            buy_price: 10.0,
+            sell_price: 20.0,
+            quantity,
+            profit,
+            roi: 0.0,
+            taxes: 0.0,
+            transport_cost: 0.0,
+        }
+    }
+
+    #[test]
+    fn budget_knapsack_takes_full_quantity_when_affordable() {
+        let opportunities = vec![opp("T1", 10, 100.0)];
+        let per_unit_profit = vec![10.0];
+        let per_unit_cost = vec![5.0];
+
+        let (profit, selected) =
+            TradeScanner::budget_knapsack(&opportunities, &per_unit_profit, &per_unit_cost, 1000.0);
+
+        assert_eq!(selected, vec![(0, 10)]);
+        assert_eq!(profit, 100.0);
+    }
+
+    #[test]
+    fn budget_knapsack_trims_quantity_to_fit_budget() {
+        let opportunities = vec![opp("T1", 10, 100.0)];
+        let per_unit_profit = vec![10.0];
+        let per_unit_cost = vec![5.0];
+
+        let (_, selected) =
+            TradeScanner::budget_knapsack(&opportunities, &per_unit_profit, &per_unit_cost, 25.0);
+
+        let (_, units) = selected[0];
+        assert!(units <= 5, "should not exceed what the budget affords");
+    }
+
+    #[test]
+    fn budget_knapsack_prefers_higher_profit_density() {
+        let opportunities = vec![opp("CHEAP", 5, 5.0), opp("RICH", 5, 50.0)];
+        let per_unit_profit = vec![1.0, 10.0];
+        let per_unit_cost = vec![1.0, 1.0];
+
+        let (_, selected) =
+            TradeScanner::budget_knapsack(&opportunities, &per_unit_profit, &per_unit_cost, 5.0);
+
+        let rich_units: u32 = selected
+            .iter()
+            .filter(|(idx, _)| *idx == 1)
+            .map(|(_, units)| units)
+            .sum();
+        assert_eq!(rich_units, 5, "should fully buy the denser opportunity first");
+    }
+
+    #[test]
+    fn budget_knapsack_zero_budget_selects_nothing() {
+        let opportunities = vec![opp("T1", 10, 100.0)];
+        let per_unit_profit = vec![10.0];
+        let per_unit_cost = vec![5.0];
+
+        let (profit, selected) =
+            TradeScanner::budget_knapsack(&opportunities, &per_unit_profit, &per_unit_cost, 0.0);
+
+        assert_eq!(profit, 0.0);
+        assert!(selected.iter().all(|(_, units)| *units == 0));
+    }
+
+    #[test]
+    fn silver_round_trips_through_f64() {
+        let s = Silver::from_f64(123.456);
+        assert_eq!(s.to_f64(), 123.456);
+    }
+
+    #[test]
+    fn silver_mul_u32_scales_exactly() {
+        let s = Silver::from_f64(2.5);
+        assert_eq!(s.mul_u32(4).to_f64(), 10.0);
+    }
+
+    #[test]
+    fn silver_mul_rate_rounds_to_nearest_thousandth() {
+        let s = Silver::from_f64(100.0);
+        assert_eq!(s.mul_rate(0.065).to_f64(), 6.5);
+    }
+
+    #[test]
+    fn silver_add_and_sub_are_exact() {
+        let a = Silver::from_f64(10.1);
+        let b = Silver::from_f64(0.2);
+        assert_eq!((a + b).to_f64(), 10.3);
+        assert_eq!((a - b).to_f64(), 9.9);
+    }
+
+    #[test]
+    fn twap_weights_by_time_between_non_uniform_samples() {
+        let mut twap = TimeWeightedPrice::new();
+        twap.add_point(0, 10.0);
+        twap.add_point(5, 20.0);
+        twap.add_point(15, 30.0);
+
+        // (10 * 5 + 20 * 10) / 15
+        let expected = 250.0 / 15.0;
+        assert!((twap.twap().unwrap() - expected).abs() < 1e-9);
+    }
+
+    fn quote(item_id: &str, city: &str, price: f64, timestamp: u64) -> MarketData {
+        MarketData {
+            item_id: item_id.to_string(),
+            item_name: item_id.to_string(),
+            city: city.to_string(),
+            buy_price: price,
+            sell_price: price * 2.0,
+            quantity: 10,
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn freshness_filter_is_per_item_not_global() {
+        // 0.1 silver/zone keeps transport (12 fallback zones x 10 units = 12 silver)
+        // below the 100-silver margin, so both items' Martlock -> Caerleon legs profit.
+        let scanner = TradeScanner::new(0.1, 1000.0);
+        let market_data = vec![
+            // LOWLIQ's quotes are internally consistent but old next to HOT's.
+            quote("LOWLIQ", "Martlock", 10.0, 1_000),
+            quote("LOWLIQ", "Caerleon", 10.0, 1_000),
+            // HOT is fresh and sets the batch-wide newest timestamp.
+            quote("HOT", "Martlock", 10.0, 100_000),
+            quote("HOT", "Caerleon", 10.0, 100_000),
+        ];
+
+        let opportunities = scanner.scan_opportunities_internal(
+            market_data,
+            0.0,
+            100,
+            5_000,
+            false,
+            &ItemWeights::new(),
+            &DistanceMatrix::new(),
+        );
+
+        assert!(
+            opportunities.iter().any(|o| o.item_id == "LOWLIQ"),
+            "LOWLIQ's self-consistent quotes should survive a per-item freshness check"
+        );
+        assert!(opportunities.iter().any(|o| o.item_id == "HOT"));
+    }
+
+    #[test]
+    fn scan_opportunities_bytes_round_trips_through_postcard() {
+        // Low silver/zone so the fallback transport cost leaves the trade profitable.
+        let scanner = TradeScanner::new(0.1, 1000.0);
+        let market_data = vec![
+            quote("T1", "Martlock", 10.0, 1_000),
+            quote("T1", "Caerleon", 10.0, 1_000),
+        ];
+
+        let encoded = postcard::to_allocvec(&market_data).unwrap();
+        let result_bytes = scanner.scan_opportunities_bytes(&encoded, 0.0, 10).unwrap();
+        let opportunities: Vec<TradeOpportunity> = postcard::from_bytes(&result_bytes).unwrap();
+
+        assert!(opportunities.iter().any(|o| o.item_id == "T1"));
+    }
+
+    #[test]
+    fn transport_cost_scales_with_zones_and_item_weight() {
+        let scanner = TradeScanner::new(2.0, 1000.0);
+        let mut distance_matrix = DistanceMatrix::new();
+        distance_matrix.insert(
+            "Martlock".to_string(),
+            [("Caerleon".to_string(), 5u32)].into_iter().collect(),
+        );
+        let mut item_weights = ItemWeights::new();
+        item_weights.insert("T1".to_string(), 3.0);
+
+        let cost = scanner.estimate_transport_cost(
+            "T1",
+            "Martlock",
+            "Caerleon",
+            4,
+            &item_weights,
+            &distance_matrix,
+        );
+
+        // zones (5) * silver_per_zone (2.0) * total weight (3.0 * 4)
+        assert_eq!(cost, 5.0 * 2.0 * (3.0 * 4.0));
+    }
+
+    #[test]
+    fn transport_cost_falls_back_when_matrix_entry_is_missing() {
+        let scanner = TradeScanner::new(1.0, 1000.0);
+
+        let cost = scanner.estimate_transport_cost(
+            "UNKNOWN",
+            "Martlock",
+            "Caerleon",
+            1,
+            &ItemWeights::new(),
+            &DistanceMatrix::new(),
+        );
+
+        // falls back to 12 zones and a 1.0 item weight
+        assert_eq!(cost, 12.0 * 1.0 * 1.0);
+    }
+
+    #[test]
+    fn plan_route_picks_the_profitable_three_city_cycle() {
+        let scanner = TradeScanner::new(1.0, 1000.0);
+        let market_data = vec![
+            // A -> B via X1
+            quote("X1", "A", 1.0, 0),
+            quote("X1", "B", 50.0, 0),
+            // B -> C via X2
+            quote("X2", "B", 1.0, 0),
+            quote("X2", "C", 50.0, 0),
+            // C -> A via X3
+            quote("X3", "C", 1.0, 0),
+            quote("X3", "A", 50.0, 0),
+        ];
+
+        let route = scanner
+            .plan_route_internal(
+                market_data,
+                "A".to_string(),
+                3,
+                10_000.0,
+                &ItemWeights::new(),
+                &DistanceMatrix::new(),
+            )
+            .unwrap();
+
+        let hops: Vec<(&str, &str)> = route
+            .iter()
+            .map(|leg| (leg.from_city.as_str(), leg.to_city.as_str()))
+            .collect();
+        assert_eq!(hops, vec![("A", "B"), ("B", "C"), ("C", "A")]);
+    }
+
+    #[test]
+    fn plan_route_rejects_too_many_hops() {
+        let scanner = TradeScanner::new(1.0, 1000.0);
+        let market_data = vec![quote("X1", "A", 1.0, 0), quote("X1", "B", 50.0, 0)];
+
+        let result = scanner.plan_route_internal(
+            market_data,
+            "A".to_string(),
+            9,
+            10_000.0,
+            &ItemWeights::new(),
+            &DistanceMatrix::new(),
+        );
+
+        assert!(result.is_err());
+    }
+}